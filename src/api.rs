@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 
-use eyre::{eyre, Result};
+use eyre::{eyre, Context, Result};
 use quote::ToTokens;
 use serde::{Deserialize, Serialize};
 use syn::{
@@ -32,6 +33,10 @@ pub struct Variant {
 pub struct Record {
     pub name: String,
     pub fields: Vec<Field>,
+
+    /// The `NAME_write`/`NAME_read` round-trip functions, if this record was tagged
+    /// `#[serialize]` and the matching functions were found.
+    pub serialize: Option<SerializeFns>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -45,6 +50,23 @@ pub struct Field {
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Opaque {
     pub name: String,
+
+    /// The exported function that frees the underlying Rust allocation, if one was
+    /// found. The Perl template wires this up as the type's `DESTROY` method.
+    pub destructor: Option<String>,
+
+    /// The `NAME_write`/`NAME_read` round-trip functions, if this opaque was tagged
+    /// `#[serialize]` and the matching functions were found.
+    pub serialize: Option<SerializeFns>,
+}
+
+/// The pair of functions that serialize a `#[serialize]` type to and from a byte buffer,
+/// following the `NAME_write(*const NAME) -> array<u8>` / `NAME_read(*const u8, usize) -> NAME*`
+/// convention.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SerializeFns {
+    pub write: String,
+    pub read: String,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -53,30 +75,177 @@ pub struct Library {
     pub enums: Vec<Enum>,
     pub records: Vec<Record>,
     pub opaques: Vec<Opaque>,
+
+    /// Names of exported functions tagged `#[destructor]`, collected during the visit
+    /// and resolved against `opaques` in `resolve_destructors`.
+    #[serde(skip)]
+    destructor_fns: Vec<String>,
+
+    /// Names of records and opaques tagged `#[serialize]`, collected during the visit
+    /// and resolved against `records`/`opaques` in `resolve_serialize`.
+    #[serde(skip)]
+    serialize_types: Vec<String>,
+
+    /// Errors raised while converting an exported function's signature, collected during the
+    /// visit since `Visit` methods can't return a `Result`. Surfaced by `Library::parse`.
+    #[serde(skip)]
+    errors: Vec<eyre::Error>,
 }
 
 impl Library {
-    pub fn remap_types(&mut self) {
-        // any opaque type will originally be NAME*, but platypus wants it to be NAME
-        // so we need to remove the pointer symbol
-        let mut depoint: HashMap<String, String> = HashMap::new();
-        for Opaque { name } in &self.opaques {
-            depoint.insert(format!("{}*", name), name.clone());
+    /// Parses `file` and builds the `Library` it describes, reporting the first error
+    /// encountered while converting an exported function's signature, if any.
+    pub fn parse(file: &syn::File) -> Result<Self> {
+        let mut library = Self::default();
+        library.visit_file(file);
+        if let Some(error) = library.errors.drain(..).next() {
+            return Err(error);
+        }
+        Ok(library)
+    }
+
+    /// Matches each opaque type to the exported function that frees it, either because
+    /// the function was tagged `#[destructor]` or because it follows the `NAME_free(NAME*)`
+    /// naming convention. Must run before `remap_types`, which strips the pointer from
+    /// `NAME*` argument types that this matching relies on.
+    pub fn resolve_destructors(&mut self) {
+        let mut destructors: HashMap<String, String> = HashMap::new();
+
+        // An explicit `#[destructor]` attribute takes precedence over the naming convention.
+        for fn_name in &self.destructor_fns {
+            let Some(function) = self.exports.iter().find(|f| &f.name == fn_name) else {
+                continue;
+            };
+            let Some(arg) = function.args.first() else {
+                continue;
+            };
+            if let Some(opaque_name) = arg.strip_suffix('*') {
+                destructors
+                    .entry(opaque_name.to_string())
+                    .or_insert_with(|| fn_name.clone());
+            }
+        }
+
+        for Opaque { name, .. } in &self.opaques {
+            if destructors.contains_key(name) {
+                continue;
+            }
+            let candidate = format!("{name}_free");
+            let is_match = self.exports.iter().any(|function| {
+                function.name == candidate
+                    && function.args == [format!("{name}*")]
+                    && function.ret == "void"
+            });
+            if is_match {
+                destructors.insert(name.clone(), candidate);
+            }
+        }
+
+        for opaque in &mut self.opaques {
+            if let Some(destructor) = destructors.remove(&opaque.name) {
+                opaque.destructor = Some(destructor);
+            }
         }
+    }
+
+    /// Matches each `#[serialize]`-tagged record or opaque to its `NAME_write`/`NAME_read`
+    /// round-trip functions. Must run before `remap_types`, which strips the pointer from
+    /// `NAME*` argument types that this matching relies on.
+    pub fn resolve_serialize(&mut self) {
+        for name in self.serialize_types.clone() {
+            let write_name = format!("{name}_write");
+            let read_name = format!("{name}_read");
+
+            let has_write = self.exports.iter().any(|function| {
+                function.name == write_name
+                    && function.args == [format!("{name}*")]
+                    && function.ret == "opaque"
+            });
+            let has_read = self.exports.iter().any(|function| {
+                function.name == read_name
+                    && function.args == ["u8*".to_string(), "usize".to_string()]
+                    && function.ret == format!("{name}*")
+            });
+
+            if !(has_write && has_read) {
+                continue;
+            }
+
+            let fns = SerializeFns {
+                write: write_name,
+                read: read_name,
+            };
+
+            if let Some(opaque) = self.opaques.iter_mut().find(|opaque| opaque.name == name) {
+                opaque.serialize = Some(fns);
+            } else if let Some(record) = self.records.iter_mut().find(|record| record.name == name)
+            {
+                record.serialize = Some(fns);
+            }
+        }
+    }
+
+    pub fn remap_types(&mut self) {
+        // an opaque type is always passed by pointer in Rust (`NAME*`), but platypus wants the
+        // named opaque type to be the bare `NAME`; any indirection beyond that single pointer
+        // (out-params, arrays of handles) is left as platypus's generic `opaque` pointer, since
+        // platypus has no way to know that e.g. a `NAME**` is a pointer to one of our opaques.
+        // A record is registered as `record(NAME)`, an alias for its by-value layout, so a
+        // `NAME*` pointer to one has to be spelled out as `record(NAME)*` instead.
+        let opaques: HashSet<String> = self.opaques.iter().map(|opaque| opaque.name.clone()).collect();
+        let records: HashSet<String> = self.records.iter().map(|record| record.name.clone()).collect();
 
         for function in &mut self.exports {
             for arg in &mut function.args {
-                if let Some(replacement) = depoint.get(arg) {
-                    arg.clone_from(replacement);
+                if let Some(replacement) = remap_pointer_type(arg, &opaques, &records) {
+                    *arg = replacement;
                 }
             }
-            if let Some(replacement) = depoint.get(&function.ret) {
-                function.ret.clone_from(replacement);
+            if let Some(replacement) = remap_pointer_type(&function.ret, &opaques, &records) {
+                function.ret = replacement;
+            }
+        }
+
+        for record in &mut self.records {
+            for field in &mut record.fields {
+                if let Some(replacement) = remap_pointer_type(&field.ty, &opaques, &records) {
+                    field.ty = replacement;
+                }
             }
         }
     }
 }
 
+// Strips exactly one level of pointer indirection off `ty` when its base name is a known
+// opaque or record type, re-expressing any remaining indirection as platypus's generic
+// `opaque` pointer. Returns `None` when `ty` isn't a pointer to a known opaque/record at all.
+fn remap_pointer_type(ty: &str, opaques: &HashSet<String>, records: &HashSet<String>) -> Option<String> {
+    let depth = ty.chars().rev().take_while(|&c| c == '*').count();
+    if depth == 0 {
+        return None;
+    }
+
+    let base = &ty[..ty.len() - depth];
+
+    if opaques.contains(base) {
+        return Some(if depth == 1 {
+            base.to_string()
+        } else {
+            format!("opaque{}", "*".repeat(depth - 1))
+        });
+    }
+
+    if records.contains(base) {
+        return Some(if depth == 1 {
+            format!("record({base})*")
+        } else {
+            format!("opaque{}", "*".repeat(depth - 1))
+        });
+    }
+
+    None
+}
+
 fn is_export(node: &ItemFn) -> bool {
     node.attrs.iter().any(|attribute| {
         let path = attribute.path();
@@ -85,6 +254,14 @@ fn is_export(node: &ItemFn) -> bool {
     })
 }
 
+fn is_destructor(node: &ItemFn) -> bool {
+    node.attrs.iter().any(|attribute| {
+        let path = attribute.path();
+
+        path.is_ident("destructor")
+    })
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, strum::EnumString)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "lowercase")]
@@ -127,6 +304,8 @@ impl From<&syn::ItemStruct> for Opaque {
     fn from(item: &syn::ItemStruct) -> Self {
         Self {
             name: item.ident.to_string(),
+            destructor: None,
+            serialize: None,
         }
     }
 }
@@ -148,6 +327,8 @@ impl From<&syn::ItemType> for Opaque {
     fn from(item: &syn::ItemType) -> Self {
         Self {
             name: item.ident.to_string(),
+            destructor: None,
+            serialize: None,
         }
     }
 }
@@ -172,6 +353,14 @@ fn is_record(item_struct: &syn::ItemStruct) -> bool {
         })
 }
 
+fn is_serialize(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attribute| {
+        let path = attribute.path();
+
+        path.is_ident("serialize")
+    })
+}
+
 fn fn_arg_type(arg: &syn::FnArg) -> Option<&Type> {
     match arg {
         syn::FnArg::Typed(pat) => Some(&pat.ty),
@@ -179,6 +368,32 @@ fn fn_arg_type(arg: &syn::FnArg) -> Option<&Type> {
     }
 }
 
+fn is_slice_arg(arg: &syn::FnArg) -> bool {
+    match arg {
+        syn::FnArg::Typed(pat) => pat.attrs.iter().any(|attribute| {
+            let path = attribute.path();
+
+            path.is_ident("slice")
+        }),
+        _ => false,
+    }
+}
+
+// Most arguments map to a single Platypus type, but a `#[slice]` argument expands into
+// the element-pointer and `usize` length pair that Platypus actually needs to marshal a `&[T]`.
+fn fn_arg_perl_ffi_types(arg: &syn::FnArg) -> Result<Vec<String>> {
+    let Some(ty) = fn_arg_type(arg) else {
+        return Ok(Vec::new());
+    };
+
+    if is_slice_arg(arg) {
+        let (ptr_ty, len_ty) = rust_slice_to_perl_ffi_types(ty)?;
+        return Ok(vec![ptr_ty, len_ty]);
+    }
+
+    rust_to_perl_ffi_type(ty).map(|ty| vec![ty])
+}
+
 fn return_type(node: &ItemFn) -> Option<&Type> {
     match &node.sig.output {
         syn::ReturnType::Type(_, ty) => Some(ty),
@@ -189,6 +404,9 @@ fn return_type(node: &ItemFn) -> Option<&Type> {
 impl<'ast> Visit<'ast> for Library {
     fn visit_item_struct(&mut self, node: &'ast syn::ItemStruct) {
         if node.is_opaque() {
+            if is_serialize(&node.attrs) {
+                self.serialize_types.push(node.ident.to_string());
+            }
             self.opaques.push(node.into());
         } else if is_record(node) {
             let name = node.ident.to_string();
@@ -201,7 +419,14 @@ impl<'ast> Visit<'ast> for Library {
                     Field { name, ty }
                 })
                 .collect();
-            self.records.push(Record { name, fields });
+            if is_serialize(&node.attrs) {
+                self.serialize_types.push(name.clone());
+            }
+            self.records.push(Record {
+                name,
+                fields,
+                serialize: None,
+            });
         }
 
         visit::visit_item_struct(self, node);
@@ -209,6 +434,9 @@ impl<'ast> Visit<'ast> for Library {
 
     fn visit_item_type(&mut self, node: &'ast syn::ItemType) {
         if node.is_opaque() {
+            if is_serialize(&node.attrs) {
+                self.serialize_types.push(node.ident.to_string());
+            }
             self.opaques.push(node.into());
         }
 
@@ -243,23 +471,36 @@ impl<'ast> Visit<'ast> for Library {
     fn visit_item_fn(&mut self, node: &'ast ItemFn) {
         if is_export(node) {
             let name = node.sig.ident.to_string();
-            let arg_types: Vec<String> = node
-                .sig
-                .inputs
-                .iter()
-                .flat_map(fn_arg_type)
-                .map(rust_to_perl_ffi_type)
-                .collect::<Result<Vec<String>>>()
-                .unwrap();
-            let ret_type = return_type(node)
-                .map(rust_to_perl_ffi_type)
-                .unwrap_or(Ok("void".to_string()))
-                .unwrap();
-            self.exports.push(Function {
-                name,
-                args: arg_types,
-                ret: ret_type,
-            });
+            let signature: Result<(Vec<String>, String)> = (|| {
+                let arg_types = node
+                    .sig
+                    .inputs
+                    .iter()
+                    .map(fn_arg_perl_ffi_types)
+                    .collect::<Result<Vec<Vec<String>>>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                let ret_type = return_type(node)
+                    .map(rust_to_perl_ffi_type)
+                    .unwrap_or(Ok("void".to_string()))?;
+                Ok((arg_types, ret_type))
+            })();
+
+            match signature.wrap_err_with(|| format!("in exported function `{name}`")) {
+                Ok((arg_types, ret_type)) => {
+                    if is_destructor(node) {
+                        self.destructor_fns.push(name.clone());
+                    }
+
+                    self.exports.push(Function {
+                        name,
+                        args: arg_types,
+                        ret: ret_type,
+                    });
+                }
+                Err(error) => self.errors.push(error),
+            }
         }
 
         // Delegate to the default impl to visit any nested functions.
@@ -267,12 +508,23 @@ impl<'ast> Visit<'ast> for Library {
     }
 }
 
+/// The user-supplied `rust_type -> platypus_type` table loaded from `--typemap`. Set once via
+/// `set_typemap` before the crate file is parsed; `rust_path_to_perl_ffi_type` consults it
+/// before falling back to the built-in conversion rules.
+static TYPEMAP: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Registers the `--typemap` table. Must be called, if at all, before parsing begins; later
+/// calls are ignored.
+pub fn set_typemap(typemap: HashMap<String, String>) {
+    let _ = TYPEMAP.set(typemap);
+}
+
 // Function to convert Rust types to Platypus FFI types.
 // Platypus supports most basic rust types, so those we can just pass through.
 fn rust_to_perl_ffi_type(ty: &Type) -> Result<String> {
     match ty {
         Type::Array(ty) => rust_array_to_perl_ffi_type(ty),
-        Type::BareFn(_) => Err(eyre!("function pointers are not supported")),
+        Type::BareFn(ty) => rust_barefn_to_perl_ffi_type(ty),
         Type::Group(_) => Err(eyre!("grouped types are not supported")),
         Type::ImplTrait(_) => Err(eyre!("impl trait is not supported")),
         Type::Infer(_) => Err(eyre!("inferred types are not supported")),
@@ -282,7 +534,9 @@ fn rust_to_perl_ffi_type(ty: &Type) -> Result<String> {
         Type::Path(ty) => rust_path_to_perl_ffi_type(ty),
         Type::Ptr(ty) => rust_pointer_to_perl_ffi_type(ty),
         Type::Reference(_) => Err(eyre!("references are not supported")),
-        Type::Slice(_) => Err(eyre!("slices are not supported")),
+        Type::Slice(_) => Err(eyre!(
+            "slices are not supported directly; annotate the argument with #[slice] to pass it as a pointer+length pair"
+        )),
         Type::TraitObject(_) => Err(eyre!("trait objects are not supported")),
         Type::Tuple(_) => Err(eyre!("tuples are not supported")),
         Type::Verbatim(_) => Err(eyre!("verbatim types are not supported")),
@@ -311,16 +565,311 @@ fn rust_pointer_to_perl_ffi_type(ty: &syn::TypePtr) -> Result<String> {
     Ok(format!("{}*", elem_ty))
 }
 
+// Expands a `#[slice]`-tagged `&[T]` (or bare `[T]`) argument into the element-pointer and
+// `usize` length pair Platypus uses to marshal it.
+fn rust_slice_to_perl_ffi_types(ty: &Type) -> Result<(String, String)> {
+    let elem = match ty {
+        Type::Reference(reference) => match reference.elem.as_ref() {
+            Type::Slice(slice) => &slice.elem,
+            _ => return Err(eyre!("#[slice] requires a slice type, e.g. `&[T]`")),
+        },
+        Type::Slice(slice) => &slice.elem,
+        _ => return Err(eyre!("#[slice] requires a slice type, e.g. `&[T]`")),
+    };
+
+    let elem_ty = rust_to_perl_ffi_type(elem)?;
+    Ok((format!("{elem_ty}*"), "usize".to_string()))
+}
+
 fn rust_path_to_perl_ffi_type(ty: &syn::TypePath) -> Result<String> {
-    // special case array<T> to T[] in platypus
+    let path = ty.path.to_token_stream().to_string();
+    if let Some(mapped) = TYPEMAP.get().and_then(|typemap| typemap.get(&path)) {
+        return Ok(mapped.clone());
+    }
+
     if let Some(segment) = ty.path.segments.iter().next() {
+        // special case array<T> to T[] in platypus
         if segment.ident == "array" {
             if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
                 if let syn::GenericArgument::Type(ty) = args.args.iter().next().unwrap() {
-                    return rust_to_perl_ffi_type(ty).map(|ty| format!("{}[]", ty));
+                    let elem_ty = rust_to_perl_ffi_type(ty)?;
+                    // byte buffers are handed to Perl as an opaque pointer rather than a
+                    // native array, since Platypus can't size a `u8[]` return value ahead of time
+                    if elem_ty == "u8" {
+                        return Ok("opaque".to_string());
+                    }
+                    return Ok(format!("{}[]", elem_ty));
                 }
             }
         }
+
+        // `Option<extern "C" fn(...)>` is how a nullable callback is spelled in Rust; Platypus
+        // closures are inherently nullable, so the `Option` wrapper carries no extra meaning here.
+        if segment.ident == "Option" {
+            if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(syn::GenericArgument::Type(Type::BareFn(bare_fn))) =
+                    args.args.iter().next()
+                {
+                    return rust_barefn_to_perl_ffi_type(bare_fn);
+                }
+            }
+        }
+    }
+    Ok(path)
+}
+
+fn is_extern_c_abi(abi: &Option<syn::Abi>) -> bool {
+    matches!(abi, Some(syn::Abi { name: Some(name), .. }) if name.value() == "C")
+}
+
+fn rust_barefn_to_perl_ffi_type(ty: &syn::TypeBareFn) -> Result<String> {
+    if !is_extern_c_abi(&ty.abi) {
+        return Err(eyre!(
+            "callback arguments must be declared `extern \"C\" fn(...)`; a callback with the \
+             default Rust ABI is not FFI-safe"
+        ));
+    }
+
+    let args = ty
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| {
+            let label = arg
+                .name
+                .as_ref()
+                .map(|(ident, _)| ident.to_string())
+                .unwrap_or_else(|| format!("#{index}"));
+            rust_to_perl_ffi_type(&arg.ty)
+                .wrap_err_with(|| format!("unsupported type for callback argument {label}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let ret = match &ty.output {
+        syn::ReturnType::Type(_, ty) => {
+            rust_to_perl_ffi_type(ty).wrap_err("unsupported type for callback return value")?
+        }
+        syn::ReturnType::Default => "void".to_string(),
+    };
+
+    Ok(format!("({})->{ret}", args.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn remap_pointer_type_strips_single_level_for_known_opaque() {
+        let opaques = names(&["Blob"]);
+        let records = names(&[]);
+        assert_eq!(
+            remap_pointer_type("Blob*", &opaques, &records),
+            Some("Blob".to_string())
+        );
+    }
+
+    #[test]
+    fn remap_pointer_type_collapses_extra_indirection_to_opaque() {
+        let opaques = names(&["Blob"]);
+        let records = names(&[]);
+        assert_eq!(
+            remap_pointer_type("Blob**", &opaques, &records),
+            Some("opaque*".to_string())
+        );
+        assert_eq!(
+            remap_pointer_type("Blob***", &opaques, &records),
+            Some("opaque**".to_string())
+        );
+    }
+
+    #[test]
+    fn remap_pointer_type_maps_record_pointer_to_record_alias() {
+        let opaques = names(&[]);
+        let records = names(&["Point"]);
+        assert_eq!(
+            remap_pointer_type("Point*", &opaques, &records),
+            Some("record(Point)*".to_string())
+        );
+        assert_eq!(
+            remap_pointer_type("Point**", &opaques, &records),
+            Some("opaque*".to_string())
+        );
+    }
+
+    #[test]
+    fn remap_pointer_type_ignores_unknown_or_non_pointer_types() {
+        let opaques = names(&["Blob"]);
+        let records = names(&["Point"]);
+        assert_eq!(remap_pointer_type("Blob", &opaques, &records), None);
+        assert_eq!(remap_pointer_type("i32*", &opaques, &records), None);
+        assert_eq!(remap_pointer_type("i32", &opaques, &records), None);
+    }
+
+    #[test]
+    fn typemap_override_takes_precedence_over_built_in_conversion() {
+        let mut typemap = HashMap::new();
+        typemap.insert("MyInt".to_string(), "int64".to_string());
+        set_typemap(typemap);
+
+        let ty: syn::TypePath = syn::parse_str("MyInt").unwrap();
+        assert_eq!(rust_path_to_perl_ffi_type(&ty).unwrap(), "int64".to_string());
+    }
+
+    #[test]
+    fn slice_arg_expands_to_pointer_and_length_pair() {
+        let ty: Type = syn::parse_str("&[i32]").unwrap();
+        let (ptr_ty, len_ty) = rust_slice_to_perl_ffi_types(&ty).unwrap();
+        assert_eq!(ptr_ty, "i32*");
+        assert_eq!(len_ty, "usize");
+    }
+
+    #[test]
+    fn barefn_rejects_non_c_abi() {
+        let ty: syn::TypeBareFn = syn::parse_str("fn(i32) -> i32").unwrap();
+        assert!(rust_barefn_to_perl_ffi_type(&ty).is_err());
+    }
+
+    #[test]
+    fn barefn_accepts_extern_c_abi() {
+        let ty: syn::TypeBareFn = syn::parse_str(r#"extern "C" fn(i32) -> i32"#).unwrap();
+        assert_eq!(
+            rust_barefn_to_perl_ffi_type(&ty).unwrap(),
+            "(i32)->i32".to_string()
+        );
+    }
+
+    #[test]
+    fn parse_reports_non_c_abi_callback_as_an_error_instead_of_panicking() {
+        let file: syn::File = syn::parse_str(
+            r#"
+            #[export]
+            #[no_mangle]
+            pub extern "C" fn bad_callback(cb: fn(i32) -> i32) -> i32 {
+                cb(1)
+            }
+            "#,
+        )
+        .unwrap();
+
+        let error = Library::parse(&file).unwrap_err();
+        assert!(error.to_string().contains("bad_callback"));
+    }
+
+    #[test]
+    fn resolve_destructors_prefers_explicit_attribute_over_naming_convention() {
+        let mut library = Library {
+            exports: vec![
+                Function {
+                    name: "Blob_custom_free".to_string(),
+                    args: vec!["Blob*".to_string()],
+                    ret: "void".to_string(),
+                },
+                Function {
+                    name: "Blob_free".to_string(),
+                    args: vec!["Blob*".to_string()],
+                    ret: "void".to_string(),
+                },
+            ],
+            opaques: vec![Opaque {
+                name: "Blob".to_string(),
+                destructor: None,
+                serialize: None,
+            }],
+            destructor_fns: vec!["Blob_custom_free".to_string()],
+            ..Default::default()
+        };
+
+        library.resolve_destructors();
+
+        assert_eq!(
+            library.opaques[0].destructor,
+            Some("Blob_custom_free".to_string())
+        );
+    }
+
+    #[test]
+    fn resolve_destructors_falls_back_to_naming_convention() {
+        let mut library = Library {
+            exports: vec![Function {
+                name: "Blob_free".to_string(),
+                args: vec!["Blob*".to_string()],
+                ret: "void".to_string(),
+            }],
+            opaques: vec![Opaque {
+                name: "Blob".to_string(),
+                destructor: None,
+                serialize: None,
+            }],
+            ..Default::default()
+        };
+
+        library.resolve_destructors();
+
+        assert_eq!(library.opaques[0].destructor, Some("Blob_free".to_string()));
+    }
+
+    #[test]
+    fn resolve_serialize_matches_record_read_write_functions() {
+        let mut library = Library {
+            exports: vec![
+                Function {
+                    name: "Point_write".to_string(),
+                    args: vec!["Point*".to_string()],
+                    ret: "opaque".to_string(),
+                },
+                Function {
+                    name: "Point_read".to_string(),
+                    args: vec!["u8*".to_string(), "usize".to_string()],
+                    ret: "Point*".to_string(),
+                },
+            ],
+            records: vec![Record {
+                name: "Point".to_string(),
+                fields: vec![],
+                serialize: None,
+            }],
+            serialize_types: vec!["Point".to_string()],
+            ..Default::default()
+        };
+
+        library.resolve_serialize();
+
+        let serialize = library.records[0].serialize.as_ref().unwrap();
+        assert_eq!(serialize.write, "Point_write");
+        assert_eq!(serialize.read, "Point_read");
+    }
+
+    #[test]
+    fn remap_types_rewrites_record_pointer_args_to_record_alias() {
+        let mut library = Library {
+            exports: vec![
+                Function {
+                    name: "Point_write".to_string(),
+                    args: vec!["Point*".to_string()],
+                    ret: "opaque".to_string(),
+                },
+                Function {
+                    name: "Point_read".to_string(),
+                    args: vec!["u8*".to_string(), "usize".to_string()],
+                    ret: "Point*".to_string(),
+                },
+            ],
+            records: vec![Record {
+                name: "Point".to_string(),
+                fields: vec![],
+                serialize: None,
+            }],
+            ..Default::default()
+        };
+
+        library.remap_types();
+
+        assert_eq!(library.exports[0].args, vec!["record(Point)*".to_string()]);
+        assert_eq!(library.exports[1].ret, "record(Point)*".to_string());
     }
-    Ok(ty.path.to_token_stream().to_string())
 }
@@ -1,6 +1,7 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use clap::Parser;
+use eyre::Context;
 use minijinja::{value::Object, Value};
 
 /// plbindgen - Generate Perl bindings for Rust code
@@ -28,6 +29,12 @@ pub struct Args {
     /// perl distribution.
     #[clap(long, default_value = "ffi/Cargo.toml")]
     pub cargo_toml: PathBuf,
+
+    /// Path to a TOML or JSON file mapping `rust_type = "platypus_type"`, for project-specific
+    /// typedefs and newtypes the built-in conversion rules don't know about. Consulted before
+    /// falling back to the built-in logic.
+    #[clap(long)]
+    pub typemap: Option<PathBuf>,
 }
 
 impl Object for Args {
@@ -64,4 +71,21 @@ impl Args {
             .clone()
             .unwrap_or_else(|| PathBuf::from(format!("lib/{}.pm", self.name.replace("::", "/"))))
     }
+
+    /// Loads the `--typemap` file, if one was given. JSON is used when the path ends in
+    /// `.json`; TOML otherwise.
+    pub fn typemap(&self) -> eyre::Result<HashMap<String, String>> {
+        let Some(path) = &self.typemap else {
+            return Ok(HashMap::new());
+        };
+
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read typemap file {}", path.display()))?;
+
+        if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            serde_json::from_str(&contents).wrap_err("failed to parse typemap as JSON")
+        } else {
+            toml::from_str(&contents).wrap_err("failed to parse typemap as TOML")
+        }
+    }
 }